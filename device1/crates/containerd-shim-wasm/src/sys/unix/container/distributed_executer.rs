@@ -1,4 +1,6 @@
 use std::cell::OnceCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use anyhow::{Context, Result};
 use libcontainer::workload::Executor as LibcontainerExecutor;
 use crate::container::{WasiContext as ContainerWasiContext,Engine,RuntimeContext,Entrypoint};
@@ -6,14 +8,387 @@ use oci_spec::image::Platform;
 use crate::sandbox::oci::WasmLayer;
 
 use distributed_executer::distributed_executer_client::DistributedExecuterClient;
+use distributed_executer::exec_chunk::Chunk;
 use tonic::Request;
-use distributed_executer::WasiContext;
+use distributed_executer::{HealthCheckRequest, ModuleHash, Mount as WasiMount, WasiContext};
 use tokio::runtime::Runtime as TokioRuntime;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+/// Hashes the raw module bytes so the server can be asked whether it
+/// already has a copy before we pay to upload them.
+fn hash_wasm_bytes(wasm_bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(wasm_bytes);
+    hasher.finalize().to_vec()
+}
 
 pub mod distributed_executer {
     include!("../../../../stubs/executer.rs");
 }
 
+/// OCI spec annotation carrying a JSON array of candidate executor
+/// endpoints, e.g. `[{"address":"http://10.0.0.1:8080","weight":2}]`.
+const ENDPOINTS_ANNOTATION: &str = "io.vsynclabs.testing/executer-endpoints";
+/// OCI spec annotation overriding how many candidates `exec` tries before
+/// giving up.
+const RETRY_COUNT_ANNOTATION: &str = "io.vsynclabs.testing/executer-retry-count";
+/// OCI spec annotation overriding the per-attempt connect/health timeout, in
+/// milliseconds.
+const TIMEOUT_MS_ANNOTATION: &str = "io.vsynclabs.testing/executer-timeout-ms";
+/// OCI spec annotation bounding the guest's linear memory + stack, in bytes.
+const MAX_MEMORY_BYTES_ANNOTATION: &str = "io.vsynclabs.testing/executer-max-memory-bytes";
+/// OCI spec annotation bounding the number of Wasm instructions the guest
+/// may execute before being terminated.
+const MAX_INSTRUCTIONS_ANNOTATION: &str = "io.vsynclabs.testing/executer-max-instructions";
+
+const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:8080";
+const DEFAULT_RETRY_COUNT: usize = 3;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Sysexits-style exit code used when the guest is killed for exceeding its
+/// memory or instruction budget, distinct from an ordinary guest failure.
+const BUDGET_EXHAUSTED_EXIT_CODE: i32 = 75; // EX_TEMPFAIL
+
+/// Reads an optional resource limit from `annotations`, defaulting to `0`
+/// ("unbounded"/"server default") when absent or unparsable.
+fn limit_from_annotations(annotations: &std::collections::HashMap<String, String>, key: &str) -> u64 {
+    annotations.get(key).and_then(|raw| raw.parse().ok()).unwrap_or(0)
+}
+
+/// A single candidate executor backend, as declared in OCI spec annotations.
+#[derive(Clone, Debug, serde::Deserialize)]
+struct EndpointConfig {
+    address: String,
+    #[serde(default = "EndpointConfig::default_weight")]
+    weight: u32,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+impl EndpointConfig {
+    fn default_weight() -> u32 {
+        1
+    }
+}
+
+/// Reads the pool of candidate executor endpoints, the retry budget, and the
+/// per-attempt timeout from the container spec's annotations, falling back
+/// to a single local endpoint so existing single-node setups keep working
+/// unannotated.
+struct EndpointRegistry {
+    endpoints: Vec<EndpointConfig>,
+    retry_count: usize,
+    timeout: Duration,
+    next: AtomicUsize,
+}
+
+impl Clone for EndpointRegistry {
+    fn clone(&self) -> Self {
+        Self {
+            endpoints: self.endpoints.clone(),
+            retry_count: self.retry_count,
+            timeout: self.timeout,
+            next: AtomicUsize::new(self.next.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl EndpointRegistry {
+    fn from_spec(spec: &oci_spec::runtime::Spec) -> Self {
+        let annotations = spec.annotations().clone().unwrap_or_default();
+
+        let endpoints = annotations
+            .get(ENDPOINTS_ANNOTATION)
+            .and_then(|raw| serde_json::from_str::<Vec<EndpointConfig>>(raw).ok())
+            .filter(|endpoints| !endpoints.is_empty())
+            .unwrap_or_else(|| {
+                vec![EndpointConfig {
+                    address: DEFAULT_ENDPOINT.to_string(),
+                    weight: EndpointConfig::default_weight(),
+                    label: None,
+                }]
+            });
+
+        let retry_count = annotations
+            .get(RETRY_COUNT_ANNOTATION)
+            .and_then(|raw| raw.parse().ok())
+            .unwrap_or(DEFAULT_RETRY_COUNT);
+
+        let timeout = annotations
+            .get(TIMEOUT_MS_ANNOTATION)
+            .and_then(|raw| raw.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_TIMEOUT);
+
+        Self {
+            endpoints,
+            retry_count,
+            timeout,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Probes every candidate's `Health` RPC and returns each distinct
+    /// healthy endpoint once, in round-robin order, starting one slot further
+    /// into the weight-expanded list than the previous call so repeated calls
+    /// spread load across the pool. `weight` only biases how often an endpoint starts
+    /// near the front of that rotation — it must not let one over-weighted
+    /// endpoint consume the whole retry budget, so the list is deduplicated
+    /// after rotating.
+    async fn healthy_candidates(&self) -> Vec<EndpointConfig> {
+        let mut weighted = Vec::new();
+        for endpoint in &self.endpoints {
+            if probe_health(&endpoint.address, self.timeout).await {
+                for _ in 0..endpoint.weight.max(1) {
+                    weighted.push(endpoint.clone());
+                }
+            }
+        }
+
+        if weighted.is_empty() {
+            return weighted;
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % weighted.len();
+        weighted.rotate_left(start);
+        dedup_by_address(weighted)
+    }
+}
+
+/// Keeps only the first occurrence of each distinct `address`, preserving
+/// order. Used to turn a weight-expanded, rotated candidate list back into
+/// one entry per endpoint before the retry budget is applied to it.
+fn dedup_by_address(weighted: Vec<EndpointConfig>) -> Vec<EndpointConfig> {
+    let mut seen = std::collections::HashSet::new();
+    weighted
+        .into_iter()
+        .filter(|endpoint| seen.insert(endpoint.address.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(address: &str, weight: u32) -> EndpointConfig {
+        EndpointConfig { address: address.to_string(), weight, label: None }
+    }
+
+    #[test]
+    fn dedup_by_address_keeps_first_occurrence_only() {
+        // A weight >= retry_count used to expand into multiple copies of
+        // the same endpoint, consuming every retry slot and starving a
+        // genuinely distinct healthy candidate behind it.
+        let weighted = vec![endpoint("a", 5), endpoint("a", 5), endpoint("a", 5), endpoint("b", 1)];
+
+        let addresses: Vec<_> = dedup_by_address(weighted).into_iter().map(|e| e.address).collect();
+
+        assert_eq!(addresses, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn dedup_by_address_is_a_no_op_on_distinct_endpoints() {
+        let weighted = vec![endpoint("a", 1), endpoint("b", 1), endpoint("c", 1)];
+
+        let addresses: Vec<_> = dedup_by_address(weighted).into_iter().map(|e| e.address).collect();
+
+        assert_eq!(addresses, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+    }
+
+    #[test]
+    fn limit_from_annotations_parses_a_present_value() {
+        let annotations = std::collections::HashMap::from([(MAX_MEMORY_BYTES_ANNOTATION.to_string(), "65536".to_string())]);
+        assert_eq!(limit_from_annotations(&annotations, MAX_MEMORY_BYTES_ANNOTATION), 65536);
+    }
+
+    #[test]
+    fn limit_from_annotations_defaults_to_zero_when_absent() {
+        let annotations = std::collections::HashMap::new();
+        assert_eq!(limit_from_annotations(&annotations, MAX_MEMORY_BYTES_ANNOTATION), 0);
+    }
+
+    #[test]
+    fn limit_from_annotations_defaults_to_zero_when_unparsable() {
+        let annotations = std::collections::HashMap::from([(MAX_MEMORY_BYTES_ANNOTATION.to_string(), "not-a-number".to_string())]);
+        assert_eq!(limit_from_annotations(&annotations, MAX_MEMORY_BYTES_ANNOTATION), 0);
+    }
+}
+
+/// Connects to `address` and calls its `Health` RPC, treating any connect
+/// failure, timeout, or unhealthy response as "not a candidate right now".
+async fn probe_health(address: &str, timeout: Duration) -> bool {
+    let probe = async {
+        let mut client = DistributedExecuterClient::connect(address.to_string()).await.ok()?;
+        client.health(Request::new(HealthCheckRequest {})).await.ok()
+    };
+
+    match tokio::time::timeout(timeout, probe).await {
+        Ok(Some(response)) => response.into_inner().healthy,
+        _ => false,
+    }
+}
+
+/// Translates the container's OCI spec mounts into the wire `Mount` list the
+/// server uses to build WASI preopens. A mount with no `source` is skipped;
+/// it can't be mapped to a host path.
+fn mounts_from_spec(spec: &oci_spec::runtime::Spec) -> Vec<WasiMount> {
+    spec.mounts()
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|mount| {
+            let host_path = mount.source()?.to_string_lossy().into_owned();
+            let guest_path = mount.destination().to_string_lossy().into_owned();
+            let read_only = mount
+                .options()
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .any(|option| option == "ro");
+
+            Some(WasiMount { host_path, guest_path, read_only })
+        })
+        .collect()
+}
+
+/// The container's configured working directory, if any.
+fn cwd_from_spec(spec: &oci_spec::runtime::Spec) -> String {
+    spec.process()
+        .as_ref()
+        .map(|process| process.cwd().to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// What happened after streaming one `RunWasiStreaming` call to completion
+/// without the guest's own exit status ending the process first.
+enum RunOutcome {
+    /// The server's module cache evicted the entry between our
+    /// `CheckModuleCache` hit and this call; no guest code ran. The caller
+    /// should resend the same request with the full module bytes.
+    CacheMiss,
+}
+
+/// Issues one `RunWasiStreaming` call and forwards the guest's stdout/stderr
+/// as they arrive, exiting the process with the guest's real status once
+/// the terminal chunk arrives. Returns `Ok(RunOutcome::CacheMiss)` on a
+/// cache miss, which the caller retries, and an error if the RPC fails or
+/// the stream ends without a result.
+async fn run_wasi_once(
+    client: &mut DistributedExecuterClient<tonic::transport::Channel>,
+    args: &[String],
+    envs: &[String],
+    wasm_bytes: Vec<u8>,
+    func_name: &str,
+    module_name: &str,
+    module_hash: Vec<u8>,
+    mounts: &[WasiMount],
+    cwd: &str,
+    max_memory_bytes: u64,
+    max_instructions: u64,
+) -> Result<RunOutcome> {
+    let wasi_context_request = WasiContext {
+        args: args.to_vec(),
+        envs: envs.to_vec(),
+        wasm_bytes,
+        func_name: func_name.to_string(),
+        module_name: module_name.to_string(),
+        module_hash,
+        mounts: mounts.to_vec(),
+        cwd: cwd.to_string(),
+        max_memory_bytes,
+        max_instructions,
+    };
+
+    let mut stream = client
+        .run_wasi_streaming(Request::new(wasi_context_request))
+        .await
+        .context("error while calling the rpc function")?
+        .into_inner();
+
+    while let Some(chunk) = stream.message().await.context("error while streaming rpc response")? {
+        match chunk.chunk {
+            Some(Chunk::Stdout(bytes)) => {
+                std::io::stdout().write_all(&bytes).expect("failed to write guest stdout");
+            }
+            Some(Chunk::Stderr(bytes)) => {
+                std::io::stderr().write_all(&bytes).expect("failed to write guest stderr");
+            }
+            Some(Chunk::Result(result)) => {
+                if result.error_kind == distributed_executer::ErrorKind::CacheMiss as i32 {
+                    return Ok(RunOutcome::CacheMiss);
+                }
+                if result.error_kind != distributed_executer::ErrorKind::None as i32 {
+                    log::error!("remote wasm execution failed ({}): {}", result.error_kind, result.message);
+                }
+                log::info!("guest peak memory usage: {} bytes", result.memory_high_water_mark);
+                if result.budget_exhausted {
+                    std::process::exit(BUDGET_EXHAUSTED_EXIT_CODE);
+                }
+                std::process::exit(result.status);
+            }
+            None => {}
+        }
+    }
+
+    anyhow::bail!("executor endpoint closed the stream without a result")
+}
+
+/// Runs the guest on a single candidate endpoint: connects, negotiates the
+/// module cache, streams stdout/stderr, and exits the process with the
+/// guest's real status on success. Returns an error (rather than exiting or
+/// panicking) on connect/RPC failure so the caller can fail over to the
+/// next candidate.
+async fn run_on_endpoint(
+    address: &str,
+    connect_timeout: Duration,
+    args: &[String],
+    envs: &[String],
+    wasm_bytes: &[u8],
+    func_name: &str,
+    module_name: &str,
+    mounts: &[WasiMount],
+    cwd: &str,
+    max_memory_bytes: u64,
+    max_instructions: u64,
+) -> Result<()> {
+    let mut client = tokio::time::timeout(connect_timeout, DistributedExecuterClient::connect(address.to_string()))
+        .await
+        .context("timed out connecting to executor endpoint")?
+        .context("failed to connect to gRPC server")?;
+
+    let module_hash = hash_wasm_bytes(wasm_bytes);
+
+    let cache_status = client
+        .check_module_cache(Request::new(ModuleHash { sha256: module_hash.clone() }))
+        .await
+        .context("failed to check module cache")?
+        .into_inner();
+
+    // Only pay to upload the module bytes when the server doesn't already
+    // have a copy of them.
+    let upload = if cache_status.cached { Vec::new() } else { wasm_bytes.to_vec() };
+
+    let outcome = run_wasi_once(
+        &mut client, args, envs, upload, func_name, module_name, module_hash.clone(), mounts, cwd, max_memory_bytes, max_instructions,
+    )
+    .await?;
+
+    match outcome {
+        // The cache check raced an eviction; resend once with the full
+        // bytes instead of treating this endpoint as failed.
+        RunOutcome::CacheMiss => {}
+    }
+
+    let outcome = run_wasi_once(
+        &mut client, args, envs, wasm_bytes.to_vec(), func_name, module_name, module_hash, mounts, cwd, max_memory_bytes, max_instructions,
+    )
+    .await?;
+
+    match outcome {
+        RunOutcome::CacheMiss => anyhow::bail!("executor endpoint reported a cache miss twice in a row"),
+    }
+}
+
 #[derive(Clone)]
 enum InnerExecutor {
     Wasm,
@@ -27,13 +402,18 @@ pub struct DistributedExecuter<E: Engine> {
     inner: OnceCell<InnerExecutor>,
     wasm_layers: Vec<WasmLayer>,
     platform: Platform,
+    /// Built from the container spec on the first `exec()` call and reused
+    /// on every later call to the same executor, so `healthy_candidates`'
+    /// round-robin rotation actually persists across calls instead of
+    /// restarting from scratch every time.
+    registry: OnceCell<EndpointRegistry>,
 }
 
 
 impl<E: Engine> LibcontainerExecutor for DistributedExecuter<E> {
     fn exec(&self, spec: &oci_spec::runtime::Spec) -> Result<(), libcontainer::workload::ExecutorError> {
 
-        let server_address="http://127.0.0.1:8080";
+        let registry = self.registry.get_or_init(|| EndpointRegistry::from_spec(spec));
 
         let wasi_context = &self.ctx(spec);
 
@@ -53,30 +433,51 @@ impl<E: Engine> LibcontainerExecutor for DistributedExecuter<E> {
 
         let func_name = func;
 
-        let tokio_runtime = TokioRuntime::new().expect("failed to create tokio runtime");
-
-        tokio_runtime.block_on(
-            async {
+        let mounts = mounts_from_spec(spec);
+        let cwd = cwd_from_spec(spec);
 
-                let mut client = DistributedExecuterClient::connect(server_address).await.expect("failed to connect to gRPC server");
+        let annotations = spec.annotations().clone().unwrap_or_default();
+        let max_memory_bytes = limit_from_annotations(&annotations, MAX_MEMORY_BYTES_ANNOTATION);
+        let max_instructions = limit_from_annotations(&annotations, MAX_INSTRUCTIONS_ANNOTATION);
 
-                let wasi_context_request = WasiContext {
-                    args:args,
-                    envs:envs,
-                    wasm_bytes:wasm_bytes,
-                    func_name:func_name,
-                    module_name:module_name,
-                };
+        let tokio_runtime = TokioRuntime::new().expect("failed to create tokio runtime");
 
-                let request = Request::new(wasi_context_request);
+        let result: Result<()> = tokio_runtime.block_on(async {
+            let candidates = registry.healthy_candidates().await;
 
-                let response = client.run_wasi(request).await.expect("error while calling the rpc function");
+            let mut last_error = None;
 
-                println!("{:?}",response);
+            for endpoint in candidates.iter().take(registry.retry_count.max(1)) {
+                match run_on_endpoint(
+                    &endpoint.address,
+                    registry.timeout,
+                    &args,
+                    &envs,
+                    &wasm_bytes,
+                    &func_name,
+                    &module_name,
+                    &mounts,
+                    &cwd,
+                    max_memory_bytes,
+                    max_instructions,
+                ).await {
+                    Ok(()) => unreachable!("run_on_endpoint exits the process on success"),
+                    Err(err) => {
+                        log::warn!("executor endpoint {} failed, trying next candidate: {err:#}", endpoint.address);
+                        last_error = Some(err);
+                    }
+                }
+            }
 
-                return Ok(());
+            match last_error {
+                Some(err) => Err(err.context("all executor endpoints exhausted")),
+                None => Err(anyhow::anyhow!("no healthy executor endpoints available")),
             }
-        )
+        });
+
+        // Propagate the failure to the shim's caller instead of aborting
+        // the process; a single bad run shouldn't take the whole shim down.
+        result.map_err(libcontainer::workload::ExecutorError::Execution)
     }
 
     fn setup_envs(&self, envs: std::collections::HashMap<String, String>) -> Result<(), libcontainer::workload::ExecutorSetEnvsError> {
@@ -95,6 +496,7 @@ impl<E: Engine> DistributedExecuter<E> {
             inner:Default::default(),
             wasm_layers,
             platform,
+            registry: Default::default(),
         }
     }
 
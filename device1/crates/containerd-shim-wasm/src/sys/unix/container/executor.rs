@@ -70,7 +70,11 @@ impl<E: Engine> LibcontainerExecutor for Executor<E> {
                     Ok(code) => std::process::exit(code),
                     Err(err) => {
                         log::info!("error running start function: {err}");
-                        std::process::exit(137)
+                        // Internal/trap failures map to sysexits' EX_SOFTWARE
+                        // rather than the previous unconditional 137, so a
+                        // guest failure is distinguishable from, e.g., a
+                        // signal-terminated container.
+                        std::process::exit(70)
                     }
                 };
             }
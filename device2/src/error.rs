@@ -0,0 +1,94 @@
+use crate::executer::ErrorKind;
+
+/// Distinguishes why a guest run failed, analogous to the Enarx workload
+/// error model, so callers get a precise exit code instead of one opaque
+/// catch-all.
+#[derive(Debug)]
+pub enum WasmExecError {
+    /// The request itself was malformed (e.g. a `module_hash` that doesn't
+    /// match the uploaded `wasm_bytes`).
+    Config(String),
+    /// WAMR couldn't parse/validate the module or create an instance of it.
+    Instantiate(String),
+    /// The requested export doesn't exist on the module.
+    ExportNotFound(String),
+    /// The guest trapped or otherwise failed mid-call.
+    Call(String),
+    /// Setting up stdio pipes or reading from them failed.
+    Io(String),
+    /// The guest was terminated for exceeding its memory or instruction
+    /// budget.
+    BudgetExhausted(String),
+    /// The client's `CheckModuleCache` check reported a hit, but the module
+    /// was evicted from the cache before the run RPC arrived. Not the
+    /// client's fault; it should resend the request with `wasm_bytes` set.
+    CacheMiss,
+}
+
+impl std::fmt::Display for WasmExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WasmExecError::Config(msg) => write!(f, "configuration error: {msg}"),
+            WasmExecError::Instantiate(msg) => write!(f, "module instantiation failed: {msg}"),
+            WasmExecError::ExportNotFound(msg) => write!(f, "export not found: {msg}"),
+            WasmExecError::Call(msg) => write!(f, "guest call failed: {msg}"),
+            WasmExecError::Io(msg) => write!(f, "i/o error: {msg}"),
+            WasmExecError::BudgetExhausted(msg) => write!(f, "resource budget exhausted: {msg}"),
+            WasmExecError::CacheMiss => write!(f, "module cache entry was evicted before the run started"),
+        }
+    }
+}
+
+impl std::error::Error for WasmExecError {}
+
+/// Maps a [`WasmExecError`] to a sysexits-style process exit code.
+impl From<&WasmExecError> for i32 {
+    fn from(err: &WasmExecError) -> Self {
+        match err {
+            WasmExecError::Config(_) => 78,         // EX_CONFIG
+            WasmExecError::Instantiate(_) => 70,    // EX_SOFTWARE
+            WasmExecError::ExportNotFound(_) => 69, // EX_UNAVAILABLE
+            WasmExecError::Call(_) => 70,           // EX_SOFTWARE
+            WasmExecError::Io(_) => 74,              // EX_IOERR
+            WasmExecError::BudgetExhausted(_) => 75, // EX_TEMPFAIL
+            WasmExecError::CacheMiss => 75,          // EX_TEMPFAIL: retry, nothing ran
+        }
+    }
+}
+
+impl From<&WasmExecError> for ErrorKind {
+    fn from(err: &WasmExecError) -> Self {
+        match err {
+            WasmExecError::Config(_) => ErrorKind::Config,
+            WasmExecError::Instantiate(_) => ErrorKind::Instantiate,
+            WasmExecError::ExportNotFound(_) => ErrorKind::ExportNotFound,
+            WasmExecError::Call(_) => ErrorKind::Trap,
+            WasmExecError::Io(_) => ErrorKind::Io,
+            WasmExecError::BudgetExhausted(_) => ErrorKind::BudgetExhausted,
+            WasmExecError::CacheMiss => ErrorKind::CacheMiss,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_and_error_kind_mappings_agree_per_variant() {
+        let cases: &[(WasmExecError, i32, ErrorKind)] = &[
+            (WasmExecError::Config(String::new()), 78, ErrorKind::Config),
+            (WasmExecError::Instantiate(String::new()), 70, ErrorKind::Instantiate),
+            (WasmExecError::ExportNotFound(String::new()), 69, ErrorKind::ExportNotFound),
+            (WasmExecError::Call(String::new()), 70, ErrorKind::Trap),
+            (WasmExecError::Io(String::new()), 74, ErrorKind::Io),
+            (WasmExecError::BudgetExhausted(String::new()), 75, ErrorKind::BudgetExhausted),
+            (WasmExecError::CacheMiss, 75, ErrorKind::CacheMiss),
+        ];
+
+        for (err, expected_status, expected_kind) in cases {
+            assert_eq!(i32::from(err), *expected_status, "exit code for {err:?}");
+            assert_eq!(ErrorKind::from(err), *expected_kind, "error kind for {err:?}");
+        }
+    }
+}
@@ -1,86 +1,326 @@
 
 use std::fmt::Debug;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
 
 use executer::distributed_executer_server::{DistributedExecuter, DistributedExecuterServer};
+use tokio::sync::mpsc::{self, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{once, Stream};
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
 
 use wamr_rust_sdk::function::Function;
 //wamr sdk imports
-use wamr_rust_sdk::module::Module;
-use wamr_rust_sdk::runtime::Runtime;
 use wamr_rust_sdk::instance::Instance as WamrInstance;
+use wamr_rust_sdk::module::Module;
 
 
-use executer::ExecResult;
+use executer::exec_chunk::Chunk;
+use executer::{ExecChunk, ExecResult, HealthCheckRequest, HealthCheckResponse, ModuleCacheStatus, ModuleHash};
 use wamr_rust_sdk::wasi_context::WasiCtxBuilder;
 
+mod error;
+mod module_cache;
+use error::WasmExecError;
+use module_cache::ModuleCache;
+
+/// Bounded channel capacity for streaming a guest's stdout/stderr back to
+/// the client; a guest producing output faster than the client reads it
+/// will simply block on `blocking_send` until the client catches up.
+const CHUNK_CHANNEL_CAPACITY: usize = 16;
+
+/// Instance memory budget used when the request doesn't set
+/// `max_memory_bytes`, matching the stack/heap size this server always used
+/// before per-request limits existed.
+const DEFAULT_INSTANCE_MEMORY_BYTES: u32 = 1024 * 64;
+
+/// Picks the WAMR instance memory size: the request's `max_memory_bytes`
+/// when set, otherwise the server's default.
+fn instance_memory_budget(max_memory_bytes: u64) -> u32 {
+    if max_memory_bytes == 0 {
+        DEFAULT_INSTANCE_MEMORY_BYTES
+    } else {
+        max_memory_bytes.min(u32::MAX as u64) as u32
+    }
+}
+
+/// WAMR reports instruction-limit and out-of-memory traps as ordinary call
+/// errors; this sniffs the trap message for them so they're reported as
+/// `BudgetExhausted` (and the caller knows to retry with a larger budget)
+/// rather than as a generic, unbounded-looking failure.
+///
+/// The substrings below are not pinned against a captured real WAMR error
+/// string in this tree (no WAMR runtime available to produce one here); if
+/// WAMR's actual wording doesn't contain one of them, a budget-killed guest
+/// is misreported as a plain `Instantiate`/`Call` failure instead. Update
+/// this list (and the test below) the first time a real trap message is
+/// observed not to match.
+fn classify_runtime_error(message: String, default: fn(String) -> WasmExecError) -> WasmExecError {
+    let lower = message.to_lowercase();
+    if lower.contains("instruction") || lower.contains("out of memory") || lower.contains("alloc") {
+        WasmExecError::BudgetExhausted(message)
+    } else {
+        default(message)
+    }
+}
+
 
 pub mod executer {
     include!("../stubs/executer.rs");
 }
 
-#[derive(Debug,Default)]
 pub struct Device2Executer {
+    module_cache: ModuleCache,
 }
 
+impl Debug for Device2Executer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Device2Executer").finish()
+    }
+}
 
 impl Device2Executer {
     fn new() -> Self {
-        return Device2Executer::default()
+        Self {
+            module_cache: ModuleCache::new(),
+        }
+    }
+}
+
+/// Builds the `ExecResult` sent back for a run that failed before the guest
+/// produced its own exit status.
+fn exec_result_for_error(err: &WasmExecError, memory_high_water_mark: u64) -> ExecResult {
+    ExecResult {
+        status: i32::from(err),
+        error_kind: executer::ErrorKind::from(err) as i32,
+        message: err.to_string(),
+        memory_high_water_mark,
+        budget_exhausted: matches!(err, WasmExecError::BudgetExhausted(_)),
+    }
+}
+
+/// Drops `read_only` mounts from `mounts`, logging each one. WAMR's preopen
+/// API has no read-only flag, so honoring a ro mount at all would silently
+/// make it fully writable inside the guest; real specs routinely pair
+/// read-only system mounts (e.g. `/etc/resolv.conf`) with read-write ones,
+/// so failing the whole run over a single ro mount would be overkill.
+fn drop_read_only_mounts(mounts: Vec<executer::Mount>) -> Vec<executer::Mount> {
+    mounts
+        .into_iter()
+        .filter(|mount| {
+            if mount.read_only {
+                eprintln!(
+                    "dropping read-only mount {} -> {}: not supported by this server's WAMR preopen implementation",
+                    mount.host_path, mount.guest_path
+                );
+            }
+            !mount.read_only
+        })
+        .collect()
+}
+
+/// Builds the WAMR preopen arguments from the container's declared mounts.
+/// A mount whose host and guest paths match is preopened directly;
+/// otherwise it's expressed as a `guest::host` mapped dir. When `mounts` is
+/// empty, both lists are empty and the guest gets no filesystem access at
+/// all, rather than the previous blanket preopen of "/".
+///
+/// Callers must filter out any mount with `read_only` set before calling
+/// this; WAMR's preopen API (`dirs`/`map_dirs`) has no read-only flag, so
+/// there's nothing here that could honor it.
+fn wasi_preopens(mounts: &[executer::Mount]) -> (Vec<String>, Vec<String>) {
+    let mut dirs = Vec::new();
+    let mut map_dirs = Vec::new();
+
+    for mount in mounts {
+        if mount.host_path == mount.guest_path {
+            dirs.push(mount.host_path.clone());
+        } else {
+            map_dirs.push(format!("{}::{}", mount.guest_path, mount.host_path));
+        }
+    }
+
+    (dirs, map_dirs)
+}
+
+/// Reads `reader` to EOF, forwarding each chunk of bytes to `tx` wrapped by
+/// `wrap`. Runs on a dedicated OS thread since pipe reads are blocking.
+fn forward_pipe(mut reader: impl Read, tx: Sender<Result<ExecChunk, Status>>, wrap: fn(Vec<u8>) -> Chunk) {
+    let mut buf = [0u8; 4096];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                let chunk = ExecChunk { chunk: Some(wrap(buf[..n].to_vec())) };
+                if tx.blocking_send(Ok(chunk)).is_err() {
+                    break;
+                }
+            }
+            Err(err) => {
+                let _ = tx.blocking_send(Err(Status::internal(format!("failed to read guest output: {err}"))));
+                break;
+            }
+        }
     }
 }
 
 #[tonic::async_trait]
 impl DistributedExecuter for Device2Executer {
-    async fn run_wasi(
+    type RunWasiStreamingStream = Pin<Box<dyn Stream<Item = Result<ExecChunk, Status>> + Send + 'static>>;
+
+    async fn health(
+        &self,
+        _request: Request<HealthCheckRequest>,
+    ) -> std::result::Result<Response<HealthCheckResponse>, Status> {
+        Ok(Response::new(HealthCheckResponse { healthy: true }))
+    }
+
+    async fn check_module_cache(
+        &self,
+        request: Request<ModuleHash>,
+    ) -> std::result::Result<Response<ModuleCacheStatus>, Status> {
+        let hash = request.into_inner().sha256;
+        let hash: module_cache::ModuleHash = hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| Status::invalid_argument("sha256 must be 32 bytes"))?;
+
+        Ok(Response::new(ModuleCacheStatus {
+            cached: self.module_cache.contains(&hash),
+        }))
+    }
+
+    async fn run_wasi_streaming(
         &self,
         request: Request<executer::WasiContext>,
-    ) -> std::result::Result<Response<executer::ExecResult>, Status> {
-        println!("request came");
+    ) -> std::result::Result<Response<Self::RunWasiStreamingStream>, Status> {
         let request = request.into_inner();
 
         let wasm_bytes = request.wasm_bytes;
-        let module_name = request.module_name; 
+        let module_name = request.module_name;
         let func_name = request.func_name;
         let args = request.args;
-        let envs = request.envs;
+        let mut envs = request.envs;
+        let module_hash: module_cache::ModuleHash = request
+            .module_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| Status::invalid_argument("module_hash must be 32 bytes"))?;
+        if !request.cwd.is_empty() {
+            envs.push(format!("PWD={}", request.cwd));
+        }
 
+        let mounts = drop_read_only_mounts(request.mounts);
+        let (dirs, map_dirs) = wasi_preopens(&mounts);
 
-        println!("{:?}",args);
-        println!("{:?}",envs);
-        println!("{:?}",wasm_bytes);
-        println!("{}",func_name);
-        println!("{}",module_name);
+        let max_memory_bytes = request.max_memory_bytes;
+        let max_instructions = request.max_instructions;
 
-        let runtime = Runtime::new().expect("failed to create runtime");
+        let module = match self.module_cache.get_or_insert(module_hash, &module_name, &wasm_bytes) {
+            Ok(module) => module,
+            // The client's CheckModuleCache check hit, but the entry was
+            // evicted before this RPC arrived; no guest code ran, so tell
+            // the client to resend with `wasm_bytes` set instead of failing
+            // the whole stream.
+            Err(module_cache::CacheError::Miss) => {
+                let chunk = ExecChunk {
+                    chunk: Some(Chunk::Result(exec_result_for_error(&WasmExecError::CacheMiss, 0))),
+                };
+                return Ok(Response::new(Box::pin(once(Ok(chunk)))));
+            }
+            Err(module_cache::CacheError::HashMismatch) => {
+                return Err(Status::invalid_argument(
+                    WasmExecError::Config("module_hash does not match sha256(wasm_bytes)".to_string()).to_string(),
+                ));
+            }
+            Err(module_cache::CacheError::Wamr(err)) => {
+                return Err(Status::internal(
+                    classify_runtime_error(format!("{err:?}"), WasmExecError::Instantiate).to_string(),
+                ));
+            }
+        };
 
-        let mut module = Module::from_buf(&runtime, &wasm_bytes, &module_name).expect("failed to create module from bytes");
+        let (stdout_reader, stdout_writer) = os_pipe::pipe().map_err(|err| Status::internal(WasmExecError::Io(err.to_string()).to_string()))?;
+        let (stderr_reader, stderr_writer) = os_pipe::pipe().map_err(|err| Status::internal(WasmExecError::Io(err.to_string()).to_string()))?;
 
-        let wasi_ctx = WasiCtxBuilder::new().set_pre_open_path(vec!["/"], vec![])
-        .set_env_vars(envs.iter().map(String::as_str).collect())
-        .set_arguments(args.iter().map(String::as_str).collect())
-        .build();
+        let (tx, rx) = mpsc::channel(CHUNK_CHANNEL_CAPACITY);
 
-        module.set_wasi_context(wasi_ctx);
+        let stdout_tx = tx.clone();
+        std::thread::spawn(move || forward_pipe(stdout_reader, stdout_tx, Chunk::Stdout));
+        let stderr_tx = tx.clone();
+        std::thread::spawn(move || forward_pipe(stderr_reader, stderr_tx, Chunk::Stderr));
 
-        let instance = WamrInstance::new(&runtime, &module, 1024 * 64).expect("failed to create wamr instance");
+        let runtime = self.module_cache.runtime();
+        std::thread::spawn(move || {
+            // `u64` alongside each `WasmExecError` is the instance's peak
+            // memory usage at the point of failure, so a trap (particularly
+            // `BudgetExhausted`) still reports the real high-water mark
+            // instead of the `0` a caller would otherwise see for every
+            // failed run. There's no instance yet when `WamrInstance::new`
+            // itself fails, so that arm reports `0` honestly.
+            let result = (|| -> std::result::Result<(i32, u64), (WasmExecError, u64)> {
+                // Each call parses its own private `Module` from the cached,
+                // already-validated bytes rather than sharing one behind a
+                // lock; see `CachedModule`'s doc comment for why. The bytes
+                // were already proven parseable when they were cached, so
+                // this is only expected to fail if the runtime itself is out
+                // of resources.
+                let mut module = Module::from_buf(runtime, &module, &module_name)
+                    .map_err(|err| (classify_runtime_error(format!("{err:?}"), WasmExecError::Instantiate), 0))?;
 
-        let function = Function::find_export_func(&instance, &func_name).expect("failed find function");
+                let wasi_ctx = WasiCtxBuilder::new()
+                    .set_pre_open_path(dirs.iter().map(String::as_str).collect(), map_dirs.iter().map(String::as_str).collect())
+                    .set_env_vars(envs.iter().map(String::as_str).collect())
+                    .set_arguments(args.iter().map(String::as_str).collect())
+                    .set_stdio(
+                        std::io::stdin().as_raw_fd(),
+                        stdout_writer.as_raw_fd(),
+                        stderr_writer.as_raw_fd(),
+                    )
+                    .build();
 
-        let status= function.call(&instance, &vec![]).map(|_|0).map_err(|err|{
-            println!("{:?}",err);
-            err
-        }).expect("failed to call function");
-        
+                module.set_wasi_context(wasi_ctx);
 
-        let response = ExecResult {
-            status
-        };
+                let instance = WamrInstance::new(runtime, &module, instance_memory_budget(max_memory_bytes))
+                    .map_err(|err| (classify_runtime_error(format!("{err:?}"), WasmExecError::Instantiate), 0))?;
+
+                if max_instructions > 0 {
+                    instance.set_instruction_count_limit(max_instructions);
+                }
+
+                let function = Function::find_export_func(&instance, &func_name)
+                    .map_err(|err| (WasmExecError::ExportNotFound(format!("{err:?}")), instance.memory_used_bytes()))?;
+
+                let status = function
+                    .call(&instance, &vec![])
+                    .map(|_| 0)
+                    .map_err(|err| (classify_runtime_error(format!("{err:?}"), WasmExecError::Call), instance.memory_used_bytes()))?;
+
+                Ok((status, instance.memory_used_bytes()))
+            })();
 
-        println!("function call status: {}",status);
+            // Close our copies of the write ends so the forwarding threads see
+            // EOF once the guest (and any of its own copies) are done.
+            drop(stdout_writer);
+            drop(stderr_writer);
 
-        Ok(Response::new(response))
+            let exec_result = match result {
+                Ok((status, memory_high_water_mark)) => ExecResult {
+                    status,
+                    error_kind: executer::ErrorKind::None as i32,
+                    message: String::new(),
+                    memory_high_water_mark,
+                    budget_exhausted: false,
+                },
+                Err((err, memory_high_water_mark)) => exec_result_for_error(&err, memory_high_water_mark),
+            };
+            let chunk = ExecChunk { chunk: Some(Chunk::Result(exec_result)) };
+            let _ = tx.blocking_send(Ok(chunk));
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
     }
 }
 
@@ -95,3 +335,88 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
    Server::builder().add_service(DistributedExecuterServer::new(device2_executer)).serve(addr).await.expect("Failed to serve");
    Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(host_path: &str, guest_path: &str) -> executer::Mount {
+        executer::Mount { host_path: host_path.to_string(), guest_path: guest_path.to_string(), read_only: false }
+    }
+
+    fn ro_mount(host_path: &str, guest_path: &str) -> executer::Mount {
+        executer::Mount { read_only: true, ..mount(host_path, guest_path) }
+    }
+
+    #[test]
+    fn drop_read_only_mounts_keeps_writable_mounts() {
+        let mounts = drop_read_only_mounts(vec![mount("/host/data", "/host/data")]);
+        assert_eq!(mounts, vec![mount("/host/data", "/host/data")]);
+    }
+
+    #[test]
+    fn drop_read_only_mounts_drops_only_the_read_only_ones() {
+        let mounts = drop_read_only_mounts(vec![
+            ro_mount("/etc/resolv.conf", "/etc/resolv.conf"),
+            mount("/host/data", "/guest/data"),
+        ]);
+        assert_eq!(mounts, vec![mount("/host/data", "/guest/data")]);
+    }
+
+    #[test]
+    fn wasi_preopens_preopens_matching_paths_directly() {
+        let (dirs, map_dirs) = wasi_preopens(&[mount("/host/data", "/host/data")]);
+        assert_eq!(dirs, vec!["/host/data".to_string()]);
+        assert!(map_dirs.is_empty());
+    }
+
+    #[test]
+    fn wasi_preopens_maps_differing_paths() {
+        let (dirs, map_dirs) = wasi_preopens(&[mount("/host/data", "/guest/data")]);
+        assert!(dirs.is_empty());
+        assert_eq!(map_dirs, vec!["/guest/data::/host/data".to_string()]);
+    }
+
+    #[test]
+    fn wasi_preopens_is_empty_with_no_mounts() {
+        let (dirs, map_dirs) = wasi_preopens(&[]);
+        assert!(dirs.is_empty());
+        assert!(map_dirs.is_empty());
+    }
+
+    #[test]
+    fn instance_memory_budget_falls_back_to_default_when_unset() {
+        assert_eq!(instance_memory_budget(0), DEFAULT_INSTANCE_MEMORY_BYTES);
+    }
+
+    #[test]
+    fn instance_memory_budget_uses_the_requested_limit() {
+        assert_eq!(instance_memory_budget(4096), 4096);
+    }
+
+    #[test]
+    fn instance_memory_budget_saturates_at_u32_max() {
+        assert_eq!(instance_memory_budget(u64::MAX), u32::MAX);
+    }
+
+    // Best-effort fixture: these strings are what this code currently
+    // expects WAMR to say, not a captured real trap message (see the
+    // caveat on classify_runtime_error's doc comment).
+    #[test]
+    fn classify_runtime_error_detects_instruction_limit_traps() {
+        let err = classify_runtime_error("Exception: instruction count limit exceeded".to_string(), WasmExecError::Call);
+        assert!(matches!(err, WasmExecError::BudgetExhausted(_)));
+    }
+
+    #[test]
+    fn classify_runtime_error_detects_out_of_memory_traps() {
+        let err = classify_runtime_error("Exception: out of memory".to_string(), WasmExecError::Instantiate);
+        assert!(matches!(err, WasmExecError::BudgetExhausted(_)));
+    }
+
+    #[test]
+    fn classify_runtime_error_falls_back_to_default_on_unrelated_traps() {
+        let err = classify_runtime_error("Exception: unreachable".to_string(), WasmExecError::Call);
+        assert!(matches!(err, WasmExecError::Call(_)));
+    }
+}
@@ -0,0 +1,146 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use wamr_rust_sdk::module::Module;
+use wamr_rust_sdk::runtime::Runtime;
+
+/// Default number of distinct Wasm modules kept warm in the cache.
+const DEFAULT_CAPACITY: usize = 32;
+
+/// SHA-256 digest of a module's raw bytes, used as the cache key.
+pub type ModuleHash = [u8; 32];
+
+/// Hashes `wasm_bytes` the same way the client does, so both sides agree on
+/// cache keys without the module bytes ever needing to be compared directly.
+pub fn hash_wasm_bytes(wasm_bytes: &[u8]) -> ModuleHash {
+    let mut hasher = Sha256::new();
+    hasher.update(wasm_bytes);
+    hasher.finalize().into()
+}
+
+/// Wasm bytes that have already been parsed and validated by WAMR at least
+/// once, shared across calls.
+///
+/// This deliberately isn't a cached, parsed `Module`: `Module::set_wasi_context`
+/// mutates the module in place with the guest's args/envs/preopens, which
+/// differ on every call even when the bytes are identical, so a shared
+/// `Module` needs a lock held around it. That lock would have to stay held
+/// for the lifetime of the `Instance` borrowed from it — i.e. for the whole
+/// guest call — serializing every concurrent caller of the same cached
+/// module behind the slowest one. Caching the bytes instead means each call
+/// pays its own `Module::from_buf` parse, but runs its instance with no
+/// cross-call lock at all.
+pub type CachedModule = Arc<Vec<u8>>;
+
+/// Why [`ModuleCache::get_or_insert`] didn't return a usable module.
+#[derive(Debug)]
+pub enum CacheError {
+    /// `hash` wasn't cached and `wasm_bytes` was empty, meaning the caller
+    /// believed (via an earlier `CheckModuleCache` call) that it was, and
+    /// the entry was evicted in between. The caller didn't do anything
+    /// wrong; it should resend the request with the full module bytes.
+    Miss,
+    /// `wasm_bytes` was present but its SHA-256 digest doesn't match the
+    /// claimed `hash`. Rejected rather than cached, since the cache is
+    /// keyed on `hash` alone: accepting this would let a caller poison a
+    /// popular hash with different bytes, or overwrite a legitimate entry.
+    HashMismatch,
+    /// WAMR failed to parse or validate the uploaded bytes.
+    Wamr(wamr_rust_sdk::value::Error),
+}
+
+/// LRU-bounded cache of WAMR-validated module bytes, keyed by their SHA-256
+/// hash. It holds no parsed `Module`s: every run still parses its own copy
+/// with `Module::from_buf` (see [`CachedModule`]).
+///
+/// The backing `Runtime` is leaked for the process lifetime so that the
+/// `Module`s parsed from cached bytes (which borrow from it) can outlive any
+/// single RPC. Entries are held behind `Arc`, so evicting an entry from the
+/// map never drops bytes a caller is still using: the caller keeps its own
+/// clone of the `Arc` alive until it's done with it.
+pub struct ModuleCache {
+    runtime: &'static Runtime,
+    entries: Mutex<LruCache<ModuleHash, CachedModule>>,
+}
+
+impl ModuleCache {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let runtime = Runtime::new().expect("failed to create wamr runtime");
+        Self {
+            runtime: Box::leak(Box::new(runtime)),
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).expect("module cache capacity must be nonzero"),
+            )),
+        }
+    }
+
+    /// The runtime backing every module in this cache, needed to instantiate them.
+    pub fn runtime(&self) -> &'static Runtime {
+        self.runtime
+    }
+
+    /// Returns `true` if a module for `hash` is already cached.
+    pub fn contains(&self, hash: &ModuleHash) -> bool {
+        self.entries
+            .lock()
+            .expect("module cache lock poisoned")
+            .contains(hash)
+    }
+
+    /// Looks up `hash` in the cache, parsing and inserting `wasm_bytes` on a
+    /// miss. `wasm_bytes` may be empty on a hit, since the client only
+    /// uploads them after a `CheckModuleCache` miss; if it's empty and
+    /// `hash` isn't cached, the entry was evicted between the client's
+    /// check and this call, so this returns [`CacheError::Miss`] rather
+    /// than trying (and failing) to parse zero bytes as a module.
+    ///
+    /// Whenever `wasm_bytes` is used to populate the cache, its SHA-256
+    /// digest is recomputed and checked against the claimed `hash` rather
+    /// than trusting the caller's word for it — `hash` is the only identity
+    /// this cache has, so an unverified claim would let any caller poison
+    /// or overwrite another module's entry.
+    pub fn get_or_insert(
+        &self,
+        hash: ModuleHash,
+        module_name: &str,
+        wasm_bytes: &[u8],
+    ) -> Result<CachedModule, CacheError> {
+        if let Some(cached) = self
+            .entries
+            .lock()
+            .expect("module cache lock poisoned")
+            .get(&hash)
+        {
+            return Ok(Arc::clone(cached));
+        }
+
+        if wasm_bytes.is_empty() {
+            return Err(CacheError::Miss);
+        }
+
+        if hash_wasm_bytes(wasm_bytes) != hash {
+            return Err(CacheError::HashMismatch);
+        }
+
+        // Parsed here purely to validate the bytes up front, matching the
+        // cache's old failure behavior; the parsed `Module` itself is
+        // discarded immediately afterwards. See `CachedModule`'s doc
+        // comment for why callers re-parse their own copy per call
+        // instead of reusing this one.
+        Module::from_buf(self.runtime, wasm_bytes, module_name).map_err(CacheError::Wamr)?;
+        let cached: CachedModule = Arc::new(wasm_bytes.to_vec());
+
+        self.entries
+            .lock()
+            .expect("module cache lock poisoned")
+            .put(hash, Arc::clone(&cached));
+
+        Ok(cached)
+    }
+}